@@ -0,0 +1,174 @@
+//! An async (`Future`-based) counterpart to [`USBHost`].
+//!
+//! `USBHost` is strictly blocking: a call to `control_transfer` or
+//! `in_transfer` does not return until the transfer has completed (or
+//! permanently failed). That is a poor fit for a cooperative scheduler,
+//! since a single NAK-heavy interrupt endpoint can stall every other
+//! task sharing the executor.
+//!
+//! `AsyncUSBHost` mirrors the shape embassy's device-side
+//! `EndpointIn::write`/`EndpointOut::read` settled on: each method
+//! returns an associated `Future` rather than blocking, so a driver can
+//! `.await` a transfer and yield while the host controller's interrupt
+//! handler (or polling loop) drives the underlying transfer descriptor
+//! to completion and wakes the task.
+
+use core::future::Future;
+
+use crate::{Endpoint, RequestCode, RequestType, TransferError, USBHost, WValue};
+
+/// Trait for host controller interfaces that can drive transfers
+/// asynchronously.
+///
+/// Implementors are expected to register a waker for the in-flight
+/// transfer descriptor and wake it from their interrupt handler (or
+/// next `tick`) once the transfer completes, rather than blocking the
+/// calling task.
+pub trait AsyncUSBHost {
+    /// The `Future` returned by [`AsyncUSBHost::control_transfer`].
+    type ControlTransfer<'a>: Future<Output = Result<usize, TransferError>>
+    where
+        Self: 'a;
+
+    /// The `Future` returned by [`AsyncUSBHost::in_transfer`].
+    type InTransfer<'a>: Future<Output = Result<usize, TransferError>>
+    where
+        Self: 'a;
+
+    /// The `Future` returned by [`AsyncUSBHost::out_transfer`].
+    type OutTransfer<'a>: Future<Output = Result<usize, TransferError>>
+    where
+        Self: 'a;
+
+    /// Issue a control transfer with an optional data stage to `ep`,
+    /// yielding until it completes. See
+    /// [`USBHost::control_transfer`] for the argument semantics.
+    fn control_transfer<'a>(
+        &'a mut self,
+        ep: &'a mut dyn Endpoint,
+        bm_request_type: RequestType,
+        b_request: RequestCode,
+        w_value: WValue,
+        w_index: u16,
+        buf: Option<&'a mut [u8]>,
+    ) -> Self::ControlTransfer<'a>;
+
+    /// Issue a transfer from `ep` to the host, yielding until it
+    /// completes. See [`USBHost::in_transfer`] for the argument
+    /// semantics.
+    fn in_transfer<'a>(
+        &'a mut self,
+        ep: &'a mut dyn Endpoint,
+        buf: &'a mut [u8],
+    ) -> Self::InTransfer<'a>;
+
+    /// Issue a transfer from the host to `ep`, yielding until it
+    /// completes. See [`USBHost::out_transfer`] for the argument
+    /// semantics.
+    fn out_transfer<'a>(&'a mut self, ep: &'a mut dyn Endpoint, buf: &'a [u8])
+        -> Self::OutTransfer<'a>;
+}
+
+/// A `Future` that resolves immediately with a blocking transfer's
+/// result.
+///
+/// Used by the [`BlockingAdapter`] below: since a blocking `USBHost`
+/// has already run the transfer to completion by the time the adapter
+/// method returns, there is nothing left to poll for.
+pub struct PollToCompletion<T>(Option<T>);
+
+impl<T: Unpin> Future for PollToCompletion<T> {
+    type Output = T;
+
+    fn poll(
+        self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Self::Output> {
+        core::task::Poll::Ready(
+            self.get_mut()
+                .0
+                .take()
+                .expect("PollToCompletion polled after completion"),
+        )
+    }
+}
+
+/// Adapter letting a blocking [`USBHost`] satisfy [`AsyncUSBHost`] by
+/// running each transfer to completion inline and handing back a
+/// `Future` that is already resolved.
+///
+/// This is a wrapper, not a blanket `impl<T: USBHost> AsyncUSBHost for
+/// T`, so that a genuinely async host controller can implement both
+/// `USBHost` (for compatibility with existing blocking drivers) and
+/// `AsyncUSBHost` (backed by real interrupt-driven wakeups) without the
+/// two impls conflicting. Wrap an existing blocking host in
+/// `BlockingAdapter` to drive it from a driver written against the
+/// async API, at the cost of blocking the executor for the duration of
+/// each transfer.
+pub struct BlockingAdapter<T>(pub T);
+
+impl<T: USBHost> AsyncUSBHost for BlockingAdapter<T> {
+    type ControlTransfer<'a> = PollToCompletion<Result<usize, TransferError>> where Self: 'a;
+    type InTransfer<'a> = PollToCompletion<Result<usize, TransferError>> where Self: 'a;
+    type OutTransfer<'a> = PollToCompletion<Result<usize, TransferError>> where Self: 'a;
+
+    fn control_transfer<'a>(
+        &'a mut self,
+        ep: &'a mut dyn Endpoint,
+        bm_request_type: RequestType,
+        b_request: RequestCode,
+        w_value: WValue,
+        w_index: u16,
+        buf: Option<&'a mut [u8]>,
+    ) -> Self::ControlTransfer<'a> {
+        let result =
+            self.0
+                .control_transfer(ep, bm_request_type, b_request, w_value, w_index, buf);
+        PollToCompletion(Some(result))
+    }
+
+    fn in_transfer<'a>(
+        &'a mut self,
+        ep: &'a mut dyn Endpoint,
+        buf: &'a mut [u8],
+    ) -> Self::InTransfer<'a> {
+        PollToCompletion(Some(self.0.in_transfer(ep, buf)))
+    }
+
+    fn out_transfer<'a>(
+        &'a mut self,
+        ep: &'a mut dyn Endpoint,
+        buf: &'a [u8],
+    ) -> Self::OutTransfer<'a> {
+        PollToCompletion(Some(self.0.out_transfer(ep, buf)))
+    }
+}
+
+/// Async counterpart to [`crate::Driver::tick`].
+///
+/// A driver implementing this trait may `.await` transfers on its
+/// registered devices instead of blocking, letting it yield to other
+/// tasks while waiting on a NAK-heavy interrupt endpoint. Unlike
+/// `Driver`, this trait is generic over the host controller rather
+/// than taking `&mut dyn USBHost`, since `AsyncUSBHost`'s associated
+/// `Future` types are not object-safe.
+///
+/// `tick` returns a named associated `Future` type, matching
+/// `AsyncUSBHost` above, rather than being declared `async fn`: an
+/// `async fn` in a public trait trips the warn-by-default
+/// `async_fn_in_trait` lint, which this crate's `-D warnings` policy
+/// turns into a hard error.
+pub trait AsyncDriver: core::fmt::Debug {
+    /// The `Future` returned by [`AsyncDriver::tick`].
+    type Tick<'a, H>: Future<Output = Result<(), crate::DriverError>>
+    where
+        Self: 'a,
+        H: AsyncUSBHost + 'a;
+
+    /// Called regularly by the USB host to allow the driver to do any
+    /// work necessary on its registered devices; see
+    /// [`crate::Driver::tick`] for the argument semantics.
+    fn tick<'a, H>(&'a mut self, millis: usize, usbhost: &'a mut H) -> Self::Tick<'a, H>
+    where
+        H: AsyncUSBHost + 'a;
+}
@@ -0,0 +1,713 @@
+//! Device enumeration and address allocation.
+//!
+//! `Driver` and `Endpoint` describe how to talk to a device that is
+//! already attached and addressed, but the crate otherwise leaves the
+//! core host job -- noticing a newly-attached device, assigning it an
+//! address, reading its descriptors, and offering it to the registered
+//! `Driver`s -- to every HCL implementor to reinvent. `DeviceTable` and
+//! `Enumerator` provide that machinery.
+
+use crate::{DeviceDescriptor, Driver, Endpoint, RequestCode, TransferError, USBHost, WValue};
+
+/// The largest address a USB device may be assigned, per §9.2.6.3 of
+/// USB 2.0. Address 0 is reserved for devices that have not yet been
+/// addressed.
+const MAX_ADDRESS: u8 = 127;
+
+/// The number of device slots tracked by a `DeviceTable`.
+///
+/// Chosen to comfortably exceed what a single USB 2.0 hub chain can
+/// practically support on a microcontroller-class host; raise it if a
+/// deployment needs more simultaneously-attached devices.
+const NUM_DEVICE_SLOTS: usize = 16;
+
+/// What we know about a device once it has been addressed.
+#[derive(Debug, Clone)]
+pub struct DeviceState {
+    /// The address assigned to this device.
+    pub address: u8,
+
+    /// The device's descriptor, as read during enumeration.
+    pub descriptor: DeviceDescriptor,
+}
+
+/// A `DeviceTable` slot: either reserved for a device that SET_ADDRESS
+/// has just been sent to (but whose descriptor hasn't been read yet),
+/// or fully assigned.
+#[derive(Debug, Clone)]
+enum Slot {
+    /// Reserved via `DeviceTable::reserve`; awaiting `commit` or
+    /// `free`.
+    Reserved,
+
+    /// Enumeration finished; holds the device's descriptor.
+    Assigned(DeviceDescriptor),
+}
+
+/// Tracks addresses handed out to attached devices.
+///
+/// Holds a fixed-size table of device slots so that enumeration
+/// requires no heap allocation, matching the rest of this `no_std`
+/// crate. A slot's index always corresponds to `address - 1`, so the
+/// address a slot was `reserve`d with never needs to be stored
+/// alongside it.
+#[derive(Debug)]
+pub struct DeviceTable {
+    slots: [Option<Slot>; NUM_DEVICE_SLOTS],
+}
+
+impl DeviceTable {
+    /// Create an empty device table.
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { None }; NUM_DEVICE_SLOTS],
+        }
+    }
+
+    /// Reserve the lowest free address for a device that SET_ADDRESS is
+    /// about to be sent to, before its descriptor is known.
+    ///
+    /// Since a slot's index is `address - 1`, only addresses
+    /// `1..=NUM_DEVICE_SLOTS` are handed out, not the whole `1..=127`
+    /// range.
+    ///
+    /// Pair with `commit` once the descriptor has been read, or `free`
+    /// if enumeration is abandoned. Returns `None` if every slot is in
+    /// use.
+    pub fn reserve(&mut self) -> Option<u8> {
+        let (slot, address) = self
+            .slots
+            .iter_mut()
+            .zip(1..=MAX_ADDRESS)
+            .find(|(slot, _)| slot.is_none())?;
+        *slot = Some(Slot::Reserved);
+        Some(address)
+    }
+
+    /// Record `descriptor` against `addr`, which must have been
+    /// `reserve`d first.
+    ///
+    /// Returns `false` (and does nothing) if `addr` was not currently
+    /// reserved, e.g. because it was `free`d in the meantime.
+    pub fn commit(&mut self, addr: u8, descriptor: DeviceDescriptor) -> bool {
+        match self.slot_for(addr) {
+            Some(slot @ Some(Slot::Reserved)) => {
+                *slot = Some(Slot::Assigned(descriptor));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Reserve the lowest free address and record `descriptor` against
+    /// it directly, for callers that already know the descriptor up
+    /// front (e.g. tests). Equivalent to `reserve` immediately followed
+    /// by `commit`.
+    ///
+    /// Returns `None` if every slot is in use.
+    pub fn allocate(&mut self, descriptor: DeviceDescriptor) -> Option<u8> {
+        let addr = self.reserve()?;
+        let committed = self.commit(addr, descriptor);
+        debug_assert!(committed, "just-reserved address must still be reserved");
+        Some(addr)
+    }
+
+    /// Release `addr`, making it available for a future `reserve`.
+    ///
+    /// Does nothing if `addr` was not reserved or assigned.
+    pub fn free(&mut self, addr: u8) {
+        if let Some(slot) = self.slot_for(addr) {
+            *slot = None;
+        }
+    }
+
+    /// Look up the state recorded for `addr`, if it has been assigned
+    /// (i.e. `commit`ted, not merely `reserve`d).
+    pub fn get(&self, addr: u8) -> Option<DeviceState> {
+        match self.slot_for_ref(addr)? {
+            Slot::Assigned(descriptor) => Some(DeviceState {
+                address: addr,
+                descriptor: descriptor.clone(),
+            }),
+            Slot::Reserved => None,
+        }
+    }
+
+    fn index_for(addr: u8) -> Option<usize> {
+        if (1..=MAX_ADDRESS).contains(&addr) {
+            Some((addr - 1) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn slot_for(&mut self, addr: u8) -> Option<&mut Option<Slot>> {
+        self.slots.get_mut(Self::index_for(addr)?)
+    }
+
+    fn slot_for_ref(&self, addr: u8) -> Option<&Slot> {
+        self.slots.get(Self::index_for(addr)?)?.as_ref()
+    }
+}
+
+impl Default for DeviceTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The steps of the enumeration state machine for a single
+/// newly-attached device.
+///
+/// Drives SET_ADDRESS, then GET_DESCRIPTOR(device), then
+/// GET_DESCRIPTOR(config), all over `control_transfer` on endpoint 0,
+/// before offering the device to the registered `Driver`s. SET_ADDRESS
+/// goes to the default address (0); both GET_DESCRIPTOR requests go to
+/// the newly-assigned address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EnumerationState {
+    /// About to issue SET_ADDRESS to move the device off the default
+    /// address.
+    SetAddress,
+
+    /// About to read the device descriptor from the newly-assigned
+    /// address.
+    GetDeviceDescriptor,
+
+    /// About to read the configuration descriptor.
+    GetConfigDescriptor,
+}
+
+/// Drives the enumeration state machine for one newly-attached device
+/// and hands the result off to the registered `Driver`s.
+#[derive(Debug)]
+pub struct Enumerator {
+    state: EnumerationState,
+    table: DeviceTable,
+    pending_address: Option<u8>,
+    pending_descriptor: Option<DeviceDescriptor>,
+}
+
+impl Enumerator {
+    /// Create an enumerator with an empty device table.
+    pub const fn new() -> Self {
+        Self {
+            state: EnumerationState::SetAddress,
+            table: DeviceTable::new(),
+            pending_address: None,
+            pending_descriptor: None,
+        }
+    }
+
+    /// The device table backing this enumerator's address allocation.
+    pub fn devices(&self) -> &DeviceTable {
+        &self.table
+    }
+
+    /// Abandon whatever enumeration step is in progress and return to
+    /// `SetAddress`, freeing the address (if any) that had been
+    /// reserved for the device being enumerated.
+    ///
+    /// Called automatically by `step` when it returns a non-retryable
+    /// error, so most callers never need to call this directly; it's
+    /// exposed for the unusual case of abandoning an in-progress
+    /// enumeration without having seen a transfer error (e.g. the
+    /// device disconnected mid-enumeration, detected independently of
+    /// a failed transfer -- see `remove_device`).
+    pub fn reset(&mut self) {
+        if let Some(addr) = self.pending_address.take() {
+            self.table.free(addr);
+        }
+        self.pending_descriptor = None;
+        self.state = EnumerationState::SetAddress;
+    }
+
+    /// Notify the enumerator that the device at `addr` has
+    /// disconnected: frees its `DeviceTable` slot and removes it from
+    /// every registered driver in `drivers`.
+    ///
+    /// Wire this to whatever the HCL uses to detect a disconnect (a
+    /// port status-change interrupt, a VBUS sense line, etc.). Without
+    /// it, addresses handed out by `step` are never reclaimed, and a
+    /// long enough run of attach/detach cycles permanently exhausts
+    /// `DeviceTable`'s slots.
+    pub fn remove_device(&mut self, addr: u8, drivers: &mut [&mut dyn Driver]) {
+        self.table.free(addr);
+        for driver in drivers.iter_mut() {
+            driver.remove_device(addr);
+        }
+    }
+
+    /// Drive one step of enumeration for a device currently sitting at
+    /// the default address (0), using `ep0` as the default control
+    /// endpoint and `usbhost` to issue the control transfers.
+    ///
+    /// Call this repeatedly (e.g. once per `Driver::tick`) until it
+    /// returns `Ok(Some(address))`, at which point the device has been
+    /// addressed, its descriptors read, and offered to `drivers`.
+    /// Returns `Ok(None)` while enumeration is still in progress. On a
+    /// non-retryable error (see `TransferError::is_retryable`), the
+    /// enumerator calls `reset` before returning the error, so the
+    /// next `step` call starts a fresh enumeration rather than
+    /// re-issuing the same failed transfer forever; a retryable error
+    /// leaves the state machine where it was, so the caller can just
+    /// call `step` again.
+    ///
+    /// `Endpoint` has no setter for its address, so once the
+    /// `SetAddress` step completes, the caller must swap `ep0` for an
+    /// `Endpoint` addressed to the value this function will go on to
+    /// return before calling `step` again -- the `GetDeviceDescriptor`
+    /// and `GetConfigDescriptor` steps assume `ep0` already targets the
+    /// newly-assigned address and will `debug_assert` otherwise.
+    pub fn step(
+        &mut self,
+        ep0: &mut dyn Endpoint,
+        usbhost: &mut dyn USBHost,
+        drivers: &mut [&mut dyn Driver],
+    ) -> Result<Option<u8>, TransferError> {
+        let result = self.step_inner(ep0, usbhost, drivers);
+        if let Err(ref err) = result {
+            if !err.is_retryable() {
+                self.reset();
+            }
+        }
+        result
+    }
+
+    fn step_inner(
+        &mut self,
+        ep0: &mut dyn Endpoint,
+        usbhost: &mut dyn USBHost,
+        drivers: &mut [&mut dyn Driver],
+    ) -> Result<Option<u8>, TransferError> {
+        match self.state {
+            EnumerationState::SetAddress => {
+                // After a retryable error, re-send SET_ADDRESS with the
+                // address already reserved rather than reserving another.
+                let address = match self.pending_address {
+                    Some(address) => address,
+                    None => {
+                        let address = self
+                            .table
+                            .reserve()
+                            .ok_or(TransferError::Permanent(Some("no free addresses")))?;
+                        self.pending_address = Some(address);
+                        address
+                    }
+                };
+
+                usbhost.control_transfer(
+                    ep0,
+                    Default::default(),
+                    RequestCode::SetAddress,
+                    WValue::from(address as u16),
+                    0,
+                    None,
+                )?;
+
+                self.state = EnumerationState::GetDeviceDescriptor;
+                Ok(None)
+            }
+
+            EnumerationState::GetDeviceDescriptor => {
+                debug_assert_eq!(
+                    Some(ep0.address()),
+                    self.pending_address,
+                    "caller must re-point ep0 at the address SetAddress just assigned \
+                     before resuming enumeration"
+                );
+
+                let mut buf = [0u8; 18];
+                let len = usbhost.control_transfer(
+                    ep0,
+                    Default::default(),
+                    RequestCode::GetDescriptor,
+                    Default::default(),
+                    0,
+                    Some(&mut buf),
+                )?;
+
+                self.pending_descriptor = Some(
+                    DeviceDescriptor::try_from(&buf[..len])
+                        .map_err(|_| TransferError::Permanent(Some("malformed device descriptor")))?,
+                );
+                self.state = EnumerationState::GetConfigDescriptor;
+                Ok(None)
+            }
+
+            EnumerationState::GetConfigDescriptor => {
+                debug_assert_eq!(
+                    Some(ep0.address()),
+                    self.pending_address,
+                    "caller must re-point ep0 at the address SetAddress just assigned \
+                     before resuming enumeration"
+                );
+
+                let mut buf = [0u8; 9];
+                usbhost.control_transfer(
+                    ep0,
+                    Default::default(),
+                    RequestCode::GetDescriptor,
+                    Default::default(),
+                    0,
+                    Some(&mut buf),
+                )?;
+
+                let address = self
+                    .pending_address
+                    .take()
+                    .expect("GetConfigDescriptor reached without a pending address");
+                let descriptor = self
+                    .pending_descriptor
+                    .take()
+                    .expect("GetConfigDescriptor reached without a device descriptor");
+
+                let committed = self.table.commit(address, descriptor.clone());
+                debug_assert!(committed, "address reserved in SetAddress must still be reserved");
+
+                for driver in drivers.iter_mut() {
+                    if driver.want_device(&descriptor) {
+                        let _ = driver.add_device(descriptor.clone(), address);
+                    }
+                }
+
+                self.state = EnumerationState::SetAddress;
+                Ok(Some(address))
+            }
+        }
+    }
+}
+
+impl Default for Enumerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Direction, IsoPacketStatus, TransferType};
+
+    fn test_descriptor() -> DeviceDescriptor {
+        Default::default()
+    }
+
+    #[test]
+    fn allocate_returns_lowest_free_address() {
+        let mut table = DeviceTable::new();
+        assert_eq!(table.allocate(test_descriptor()), Some(1));
+        assert_eq!(table.allocate(test_descriptor()), Some(2));
+    }
+
+    #[test]
+    fn free_makes_the_lowest_address_reusable() {
+        let mut table = DeviceTable::new();
+        let first = table.allocate(test_descriptor()).unwrap();
+        let _second = table.allocate(test_descriptor()).unwrap();
+
+        table.free(first);
+
+        assert_eq!(
+            table.allocate(test_descriptor()),
+            Some(first),
+            "freeing the lowest address should make it the next one handed out"
+        );
+    }
+
+    #[test]
+    fn allocate_returns_none_once_every_slot_is_used() {
+        let mut table = DeviceTable::new();
+        for _ in 0..NUM_DEVICE_SLOTS {
+            table.allocate(test_descriptor()).unwrap();
+        }
+        assert!(table.allocate(test_descriptor()).is_none());
+    }
+
+    struct MockEndpoint {
+        address: u8,
+        in_toggle: bool,
+        out_toggle: bool,
+    }
+
+    impl Endpoint for MockEndpoint {
+        fn address(&self) -> u8 {
+            self.address
+        }
+
+        fn endpoint_num(&self) -> u8 {
+            0
+        }
+
+        fn transfer_type(&self) -> TransferType {
+            TransferType::Control
+        }
+
+        fn direction(&self) -> Direction {
+            Direction::Out
+        }
+
+        fn max_packet_size(&self) -> u16 {
+            64
+        }
+
+        fn interval_ms(&self) -> u8 {
+            0
+        }
+
+        fn in_toggle(&self) -> bool {
+            self.in_toggle
+        }
+
+        fn set_in_toggle(&mut self, toggle: bool) {
+            self.in_toggle = toggle;
+        }
+
+        fn out_toggle(&self) -> bool {
+            self.out_toggle
+        }
+
+        fn set_out_toggle(&mut self, toggle: bool) {
+            self.out_toggle = toggle;
+        }
+    }
+
+    #[derive(Default)]
+    struct MockHost {
+        last_set_address_w_value: Option<WValue>,
+    }
+
+    impl USBHost for MockHost {
+        fn control_transfer(
+            &mut self,
+            _ep: &mut dyn Endpoint,
+            _bm_request_type: crate::RequestType,
+            b_request: RequestCode,
+            w_value: WValue,
+            _w_index: u16,
+            _buf: Option<&mut [u8]>,
+        ) -> Result<usize, TransferError> {
+            if b_request == RequestCode::SetAddress {
+                self.last_set_address_w_value = Some(w_value);
+            }
+            Ok(0)
+        }
+
+        fn in_transfer(
+            &mut self,
+            _ep: &mut dyn Endpoint,
+            _buf: &mut [u8],
+        ) -> Result<usize, TransferError> {
+            Ok(0)
+        }
+
+        fn out_transfer(&mut self, _ep: &mut dyn Endpoint, _buf: &[u8]) -> Result<usize, TransferError> {
+            Ok(0)
+        }
+
+        fn iso_in_transfer(
+            &mut self,
+            _ep: &mut dyn Endpoint,
+            _bufs: &mut [&mut [u8]],
+            _statuses: &mut [IsoPacketStatus],
+        ) -> Result<(), TransferError> {
+            Ok(())
+        }
+
+        fn iso_out_transfer(
+            &mut self,
+            _ep: &mut dyn Endpoint,
+            _bufs: &[&[u8]],
+            _statuses: &mut [IsoPacketStatus],
+        ) -> Result<(), TransferError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn set_address_encodes_the_allocated_address_in_w_value() {
+        let mut enumerator = Enumerator::new();
+        let mut ep0 = MockEndpoint {
+            address: 0,
+            in_toggle: false,
+            out_toggle: false,
+        };
+        let mut host = MockHost::default();
+        let mut drivers: [&mut dyn Driver; 0] = [];
+
+        enumerator.step(&mut ep0, &mut host, &mut drivers).unwrap();
+
+        assert_eq!(host.last_set_address_w_value, Some(WValue::from(1u16)));
+    }
+
+    #[derive(Debug, Default)]
+    struct MockDriver {
+        wants: bool,
+        added: Option<(u8, DeviceDescriptor)>,
+        removed: Option<u8>,
+    }
+
+    impl Driver for MockDriver {
+        fn want_device(&self, _device: &DeviceDescriptor) -> bool {
+            self.wants
+        }
+
+        fn add_device(
+            &mut self,
+            device: DeviceDescriptor,
+            address: u8,
+        ) -> Result<(), crate::DriverError> {
+            self.added = Some((address, device));
+            Ok(())
+        }
+
+        fn remove_device(&mut self, address: u8) {
+            self.removed = Some(address);
+        }
+
+        fn tick(&mut self, _millis: usize, _usbhost: &mut dyn USBHost) -> Result<(), crate::DriverError> {
+            Ok(())
+        }
+    }
+
+    /// A `USBHost` whose every transfer fails with a non-retryable
+    /// error, for exercising `Enumerator::step`'s reset-on-error path.
+    struct FailingHost;
+
+    impl USBHost for FailingHost {
+        fn control_transfer(
+            &mut self,
+            _ep: &mut dyn Endpoint,
+            _bm_request_type: crate::RequestType,
+            _b_request: RequestCode,
+            _w_value: WValue,
+            _w_index: u16,
+            _buf: Option<&mut [u8]>,
+        ) -> Result<usize, TransferError> {
+            Err(TransferError::Stall(None))
+        }
+
+        fn in_transfer(&mut self, _ep: &mut dyn Endpoint, _buf: &mut [u8]) -> Result<usize, TransferError> {
+            Err(TransferError::Stall(None))
+        }
+
+        fn out_transfer(&mut self, _ep: &mut dyn Endpoint, _buf: &[u8]) -> Result<usize, TransferError> {
+            Err(TransferError::Stall(None))
+        }
+
+        fn iso_in_transfer(
+            &mut self,
+            _ep: &mut dyn Endpoint,
+            _bufs: &mut [&mut [u8]],
+            _statuses: &mut [IsoPacketStatus],
+        ) -> Result<(), TransferError> {
+            Err(TransferError::Stall(None))
+        }
+
+        fn iso_out_transfer(
+            &mut self,
+            _ep: &mut dyn Endpoint,
+            _bufs: &[&[u8]],
+            _statuses: &mut [IsoPacketStatus],
+        ) -> Result<(), TransferError> {
+            Err(TransferError::Stall(None))
+        }
+    }
+
+    #[test]
+    fn step_drives_full_enumeration_and_dispatches_to_drivers() {
+        let mut enumerator = Enumerator::new();
+        let mut host = MockHost::default();
+        let mut driver = MockDriver {
+            wants: true,
+            ..Default::default()
+        };
+        let mut ep0 = MockEndpoint {
+            address: 0,
+            in_toggle: false,
+            out_toggle: false,
+        };
+
+        {
+            let mut drivers: [&mut dyn Driver; 1] = [&mut driver];
+            assert_eq!(enumerator.step(&mut ep0, &mut host, &mut drivers).unwrap(), None);
+
+            // Per `step`'s documented contract, the caller re-points ep0
+            // at the newly-assigned address before resuming.
+            ep0.address = 1;
+            assert_eq!(enumerator.step(&mut ep0, &mut host, &mut drivers).unwrap(), None);
+            assert_eq!(
+                enumerator.step(&mut ep0, &mut host, &mut drivers).unwrap(),
+                Some(1)
+            );
+        }
+
+        let (address, descriptor) = driver
+            .added
+            .expect("a driver that wants the device should have been offered it");
+        assert_eq!(address, 1);
+        assert_eq!(descriptor, test_descriptor());
+        assert_eq!(enumerator.devices().get(1).unwrap().address, 1);
+    }
+
+    #[test]
+    fn non_retryable_error_resets_state_and_frees_the_reserved_address() {
+        let mut enumerator = Enumerator::new();
+        let mut failing_host = FailingHost;
+        let mut ep0 = MockEndpoint {
+            address: 0,
+            in_toggle: false,
+            out_toggle: false,
+        };
+        let mut drivers: [&mut dyn Driver; 0] = [];
+
+        let err = enumerator
+            .step(&mut ep0, &mut failing_host, &mut drivers)
+            .unwrap_err();
+        assert!(!err.is_retryable());
+
+        // The address reserved for the failed attempt must have been
+        // freed, not leaked.
+        assert!(enumerator.devices().get(1).is_none());
+
+        // A fresh attempt reserves address 1 again rather than moving
+        // on to 2, proving the state machine actually reset.
+        let mut host = MockHost::default();
+        enumerator.step(&mut ep0, &mut host, &mut drivers).unwrap();
+        assert_eq!(host.last_set_address_w_value, Some(WValue::from(1u16)));
+    }
+
+    #[test]
+    fn remove_device_frees_the_slot_and_notifies_drivers() {
+        let mut enumerator = Enumerator::new();
+        let mut host = MockHost::default();
+        let mut driver = MockDriver {
+            wants: true,
+            ..Default::default()
+        };
+        let mut ep0 = MockEndpoint {
+            address: 0,
+            in_toggle: false,
+            out_toggle: false,
+        };
+
+        {
+            let mut drivers: [&mut dyn Driver; 1] = [&mut driver];
+            enumerator.step(&mut ep0, &mut host, &mut drivers).unwrap();
+            ep0.address = 1;
+            enumerator.step(&mut ep0, &mut host, &mut drivers).unwrap();
+            enumerator.step(&mut ep0, &mut host, &mut drivers).unwrap();
+
+            enumerator.remove_device(1, &mut drivers);
+        }
+
+        assert_eq!(driver.removed, Some(1));
+        assert!(enumerator.devices().get(1).is_none());
+
+        // The address is free again for the next device to attach.
+        let mut drivers: [&mut dyn Driver; 0] = [];
+        ep0.address = 0;
+        enumerator.step(&mut ep0, &mut host, &mut drivers).unwrap();
+        assert_eq!(host.last_set_address_w_value, Some(WValue::from(1u16)));
+    }
+}
@@ -10,19 +10,90 @@
 #![no_std]
 
 pub mod descriptor;
+pub mod enumeration;
+pub mod scheduler;
 pub mod setup;
 
+#[cfg(feature = "async")]
+pub mod asynch;
+
 pub use descriptor::*;
 pub use setup::*;
 
 /// Errors that can be generated when attempting to do a USB transfer.
-#[derive(Debug)]
+///
+/// Each variant carries the real USB condition that produced it,
+/// rather than forcing callers to recover that information by parsing
+/// a message, along with an optional `&'static str` giving additional
+/// context.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TransferError {
-    /// An error that may be retried.
-    Retry(&'static str),
+    /// The endpoint returned a STALL handshake. The endpoint is now
+    /// halted and requires a `CLEAR_FEATURE(ENDPOINT_HALT)` before
+    /// further transfers will succeed.
+    Stall(Option<&'static str>),
 
-    /// A permanent error.
-    Permanent(&'static str),
+    /// The device NAK'd the transfer. This is benign and expected on
+    /// interrupt endpoints with no data ready; the transfer should
+    /// simply be retried later.
+    Nak(Option<&'static str>),
+
+    /// The transfer did not complete within the host controller's
+    /// timeout window.
+    Timeout(Option<&'static str>),
+
+    /// More data was offered than the provided buffer, or than the
+    /// endpoint's max packet size, could hold.
+    BufferOverflow(Option<&'static str>),
+
+    /// The device was no longer present on the bus when the transfer
+    /// was attempted.
+    DeviceDisconnected(Option<&'static str>),
+
+    /// The data toggle bit on the received packet did not match the
+    /// expected sequence.
+    DataToggleMismatch(Option<&'static str>),
+
+    /// A permanent error with no more specific condition code.
+    Permanent(Option<&'static str>),
+}
+
+impl TransferError {
+    /// Does this error represent a condition where simply retrying the
+    /// transfer later is reasonable?
+    ///
+    /// `Nak`, `Timeout`, and `DataToggleMismatch` are all conditions a
+    /// well-behaved retry can recover from. `Stall` requires clearing
+    /// the endpoint halt first, and the remaining variants indicate
+    /// the transfer cannot succeed as issued.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            TransferError::Nak(_) | TransferError::Timeout(_) | TransferError::DataToggleMismatch(_)
+        )
+    }
+}
+
+/// The outcome of a single packet within an isochronous transfer.
+///
+/// Isochronous endpoints have no handshake and no data toggle, so a
+/// single aggregate `TransferError` cannot represent what happened:
+/// some packets in the batch may have transferred fine while others
+/// were dropped or arrived too late for their frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IsoPacketStatus {
+    /// The packet transferred successfully, moving this many bytes.
+    Ok(usize),
+
+    /// No data was ready for this packet's frame, so it was skipped.
+    Dropped,
+
+    /// The packet arrived after its frame had already passed and was
+    /// discarded.
+    Late,
+
+    /// Some other error occurred transferring this packet.
+    Error(TransferError),
 }
 
 /// Trait for host controller interface.
@@ -43,6 +114,38 @@ pub trait USBHost {
         buf: Option<&mut [u8]>,
     ) -> Result<usize, TransferError>;
 
+    /// Issue an isochronous transfer from `ep` to the host, one packet
+    /// per entry of `bufs`.
+    ///
+    /// Isochronous transfers are bound to USB frame boundaries (one
+    /// packet per `ep.interval_ms()`) and are never retried, so unlike
+    /// `in_transfer` a failure on one packet does not fail the whole
+    /// batch: the outcome of each packet is written to the
+    /// corresponding entry of `statuses`, which must be the same
+    /// length as `bufs`. The `Result` is reserved for a failure
+    /// affecting the whole transfer, such as the device disconnecting.
+    ///
+    /// This method issues the transfer unconditionally; callers are
+    /// expected to gate calls to it on `ep.interval_ms()` having
+    /// actually elapsed, e.g. via [`scheduler::PollSchedule::poll_iso_in`].
+    fn iso_in_transfer(
+        &mut self,
+        ep: &mut dyn Endpoint,
+        bufs: &mut [&mut [u8]],
+        statuses: &mut [IsoPacketStatus],
+    ) -> Result<(), TransferError>;
+
+    /// Issue an isochronous transfer from the host to `ep`, one packet
+    /// per entry of `bufs`. See [`USBHost::iso_in_transfer`] for the
+    /// per-packet status semantics and the [`scheduler`] module for
+    /// gating calls to this method on `ep.interval_ms()`.
+    fn iso_out_transfer(
+        &mut self,
+        ep: &mut dyn Endpoint,
+        bufs: &[&[u8]],
+        statuses: &mut [IsoPacketStatus],
+    ) -> Result<(), TransferError>;
+
     /// Issue a transfer from `ep` to the host.
     ///
     /// On success, the amount of data transferred into `buf` is
@@ -100,6 +203,14 @@ pub trait Endpoint {
     /// The maximum packet size for this endpoint.
     fn max_packet_size(&self) -> u16;
 
+    /// The polling interval for this endpoint, in milliseconds,
+    /// a.k.a. bInterval (cf §9.6.6 of USB 2.0).
+    ///
+    /// Only meaningful for `Interrupt` and `Isochronous` endpoints;
+    /// `Control` and `Bulk` endpoints have no fixed polling rate, so
+    /// implementors should return `0` for them.
+    fn interval_ms(&self) -> u8;
+
     /// The data toggle sequence bit for the next transfer from the
     /// device to the host.
     fn in_toggle(&self) -> bool;
@@ -0,0 +1,412 @@
+//! A helper for polling endpoints at their declared interval.
+//!
+//! Interrupt (and isochronous) endpoints advertise a polling period via
+//! [`Endpoint::interval_ms`], but nothing in the crate tracks that
+//! period against the current time. Without it, drivers for
+//! interrupt-driven devices (HID keyboards, etc.) either busy-loop an
+//! `in_transfer` on every `Driver::tick`, or have to reimplement this
+//! bookkeeping themselves.
+//!
+//! `PollSchedule` tracks a single endpoint's next-due timestamp and
+//! tells the caller whether it's time to poll, given the `millis`
+//! value `Driver::tick` is called with. Arithmetic is done with
+//! wrapping subtraction so that it keeps working across the documented
+//! wraparound of `millis`.
+
+use crate::{Endpoint, IsoPacketStatus, TransferError, USBHost};
+
+/// Tracks when an endpoint is next due to be polled.
+#[derive(Debug, Clone, Copy)]
+pub struct PollSchedule {
+    interval_ms: usize,
+    next_due: usize,
+}
+
+impl PollSchedule {
+    /// Create a schedule for `ep`, due immediately.
+    pub fn new(ep: &dyn Endpoint, now_millis: usize) -> Self {
+        Self {
+            interval_ms: ep.interval_ms() as usize,
+            next_due: now_millis,
+        }
+    }
+
+    /// Is this endpoint due to be polled at `now_millis`?
+    ///
+    /// Uses wrapping subtraction so that a `now_millis` that has
+    /// wrapped around relative to `next_due` is still handled
+    /// correctly, as long as the wraparound is less than half of
+    /// `usize::MAX`.
+    pub fn is_due(&self, now_millis: usize) -> bool {
+        now_millis.wrapping_sub(self.next_due) as isize >= 0
+    }
+
+    /// Record that the endpoint was just polled at `now_millis`,
+    /// advancing the next-due timestamp by this endpoint's interval
+    /// (by 1 ms if the interval is 0).
+    pub fn mark_polled(&mut self, now_millis: usize) {
+        self.next_due = now_millis.wrapping_add(self.interval_ms.max(1));
+    }
+
+    /// Issue an `in_transfer` on `ep` if, and only if, this schedule's
+    /// interval has elapsed at `now_millis`.
+    ///
+    /// Bundles the due-check, the transfer, and advancing the schedule
+    /// into one call, so interrupt-endpoint drivers don't have to
+    /// hand-roll that sequence themselves. Returns `Ok(None)` without
+    /// touching `usbhost` if the interval hasn't elapsed yet.
+    pub fn poll_in(
+        &mut self,
+        now_millis: usize,
+        usbhost: &mut dyn USBHost,
+        ep: &mut dyn Endpoint,
+        buf: &mut [u8],
+    ) -> Result<Option<usize>, TransferError> {
+        if !self.is_due(now_millis) {
+            return Ok(None);
+        }
+        let len = usbhost.in_transfer(ep, buf)?;
+        self.mark_polled(now_millis);
+        Ok(Some(len))
+    }
+
+    /// Issue an `out_transfer` on `ep` if this schedule's interval has
+    /// elapsed at `now_millis`. See [`PollSchedule::poll_in`] for the
+    /// gating semantics.
+    pub fn poll_out(
+        &mut self,
+        now_millis: usize,
+        usbhost: &mut dyn USBHost,
+        ep: &mut dyn Endpoint,
+        buf: &[u8],
+    ) -> Result<Option<usize>, TransferError> {
+        if !self.is_due(now_millis) {
+            return Ok(None);
+        }
+        let len = usbhost.out_transfer(ep, buf)?;
+        self.mark_polled(now_millis);
+        Ok(Some(len))
+    }
+
+    /// Issue an isochronous in-transfer on `ep` if, and only if, this
+    /// schedule's frame boundary (one packet per `ep.interval_ms()`)
+    /// has been reached at `now_millis`.
+    ///
+    /// Isochronous endpoints have no handshake to retry against, so
+    /// unlike a regular interrupt poll there is no reason to check
+    /// `is_due` separately from issuing the transfer: this bundles the
+    /// check, the transfer, and advancing the schedule into one call.
+    /// Returns `Ok(false)` without touching `usbhost` if the frame
+    /// boundary hasn't been reached yet.
+    pub fn poll_iso_in(
+        &mut self,
+        now_millis: usize,
+        usbhost: &mut dyn USBHost,
+        ep: &mut dyn Endpoint,
+        bufs: &mut [&mut [u8]],
+        statuses: &mut [IsoPacketStatus],
+    ) -> Result<bool, TransferError> {
+        if !self.is_due(now_millis) {
+            return Ok(false);
+        }
+        usbhost.iso_in_transfer(ep, bufs, statuses)?;
+        self.mark_polled(now_millis);
+        Ok(true)
+    }
+
+    /// Issue an isochronous out-transfer on `ep` if this schedule's
+    /// frame boundary has been reached at `now_millis`. See
+    /// [`PollSchedule::poll_iso_in`] for the gating semantics.
+    pub fn poll_iso_out(
+        &mut self,
+        now_millis: usize,
+        usbhost: &mut dyn USBHost,
+        ep: &mut dyn Endpoint,
+        bufs: &[&[u8]],
+        statuses: &mut [IsoPacketStatus],
+    ) -> Result<bool, TransferError> {
+        if !self.is_due(now_millis) {
+            return Ok(false);
+        }
+        usbhost.iso_out_transfer(ep, bufs, statuses)?;
+        self.mark_polled(now_millis);
+        Ok(true)
+    }
+}
+
+/// Polls a fixed number of endpoints, each at its own
+/// [`Endpoint::interval_ms`] rate.
+///
+/// `N` is the number of endpoints tracked; callers with a varying
+/// number of interrupt endpoints should size it generously, as with
+/// `enumeration::DeviceTable`'s fixed-size slots.
+#[derive(Debug)]
+pub struct PollScheduler<const N: usize> {
+    schedules: [Option<PollSchedule>; N],
+}
+
+impl<const N: usize> PollScheduler<N> {
+    /// Create an empty scheduler.
+    pub const fn new() -> Self {
+        Self {
+            schedules: [const { None }; N],
+        }
+    }
+
+    /// Register `ep` for polling, in the first free slot.
+    ///
+    /// Returns the slot index on success, or `None` if every slot is
+    /// already in use.
+    pub fn register(&mut self, ep: &dyn Endpoint, now_millis: usize) -> Option<usize> {
+        let (slot_index, slot) = self
+            .schedules
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.is_none())?;
+        *slot = Some(PollSchedule::new(ep, now_millis));
+        Some(slot_index)
+    }
+
+    /// Stop tracking the endpoint registered at `slot_index`.
+    pub fn unregister(&mut self, slot_index: usize) {
+        if let Some(slot) = self.schedules.get_mut(slot_index) {
+            *slot = None;
+        }
+    }
+
+    /// Is the endpoint at `slot_index` due to be polled at
+    /// `now_millis`?
+    ///
+    /// Returns `false` for an unregistered slot.
+    pub fn is_due(&self, slot_index: usize, now_millis: usize) -> bool {
+        self.schedules
+            .get(slot_index)
+            .and_then(|slot| slot.as_ref())
+            .is_some_and(|schedule| schedule.is_due(now_millis))
+    }
+
+    /// Record that the endpoint at `slot_index` was just polled at
+    /// `now_millis`.
+    pub fn mark_polled(&mut self, slot_index: usize, now_millis: usize) {
+        if let Some(Some(schedule)) = self.schedules.get_mut(slot_index) {
+            schedule.mark_polled(now_millis);
+        }
+    }
+}
+
+impl<const N: usize> Default for PollScheduler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Direction, TransferType};
+
+    struct MockEndpoint {
+        interval_ms: u8,
+    }
+
+    impl Endpoint for MockEndpoint {
+        fn address(&self) -> u8 {
+            0
+        }
+
+        fn endpoint_num(&self) -> u8 {
+            1
+        }
+
+        fn transfer_type(&self) -> TransferType {
+            TransferType::Interrupt
+        }
+
+        fn direction(&self) -> Direction {
+            Direction::In
+        }
+
+        fn max_packet_size(&self) -> u16 {
+            8
+        }
+
+        fn interval_ms(&self) -> u8 {
+            self.interval_ms
+        }
+
+        fn in_toggle(&self) -> bool {
+            false
+        }
+
+        fn set_in_toggle(&mut self, _toggle: bool) {}
+
+        fn out_toggle(&self) -> bool {
+            false
+        }
+
+        fn set_out_toggle(&mut self, _toggle: bool) {}
+    }
+
+    #[test]
+    fn new_schedule_is_due_immediately() {
+        let ep = MockEndpoint { interval_ms: 10 };
+        let schedule = PollSchedule::new(&ep, 100);
+        assert!(schedule.is_due(100));
+    }
+
+    #[test]
+    fn not_due_until_the_interval_elapses() {
+        let ep = MockEndpoint { interval_ms: 10 };
+        let mut schedule = PollSchedule::new(&ep, 100);
+        schedule.mark_polled(100);
+
+        assert!(!schedule.is_due(105));
+        assert!(schedule.is_due(110));
+        assert!(schedule.is_due(111));
+    }
+
+    #[test]
+    fn is_due_survives_millis_wraparound() {
+        let ep = MockEndpoint { interval_ms: 10 };
+        let mut schedule = PollSchedule::new(&ep, usize::MAX - 2);
+        schedule.mark_polled(usize::MAX - 2);
+
+        // next_due wrapped around to `7` (= (MAX - 2) + 10, mod 2^bits).
+        assert!(!schedule.is_due(usize::MAX));
+        assert!(!schedule.is_due(2));
+        assert!(schedule.is_due(7));
+        assert!(schedule.is_due(8));
+    }
+
+    #[test]
+    fn zero_interval_still_advances_next_due() {
+        // A zero interval (e.g. a control or bulk endpoint) must not
+        // leave `is_due` permanently true, or a scheduler polling it
+        // alongside real interrupt endpoints would starve them.
+        let ep = MockEndpoint { interval_ms: 0 };
+        let mut schedule = PollSchedule::new(&ep, 100);
+        schedule.mark_polled(100);
+
+        assert!(!schedule.is_due(100));
+        assert!(schedule.is_due(101));
+    }
+
+    #[derive(Default)]
+    struct MockHost {
+        iso_in_calls: usize,
+        in_calls: usize,
+    }
+
+    impl USBHost for MockHost {
+        fn control_transfer(
+            &mut self,
+            _ep: &mut dyn Endpoint,
+            _bm_request_type: crate::RequestType,
+            _b_request: crate::RequestCode,
+            _w_value: crate::WValue,
+            _w_index: u16,
+            _buf: Option<&mut [u8]>,
+        ) -> Result<usize, TransferError> {
+            Ok(0)
+        }
+
+        fn in_transfer(&mut self, _ep: &mut dyn Endpoint, buf: &mut [u8]) -> Result<usize, TransferError> {
+            self.in_calls += 1;
+            Ok(buf.len())
+        }
+
+        fn out_transfer(&mut self, _ep: &mut dyn Endpoint, _buf: &[u8]) -> Result<usize, TransferError> {
+            Ok(0)
+        }
+
+        fn iso_in_transfer(
+            &mut self,
+            _ep: &mut dyn Endpoint,
+            _bufs: &mut [&mut [u8]],
+            _statuses: &mut [IsoPacketStatus],
+        ) -> Result<(), TransferError> {
+            self.iso_in_calls += 1;
+            Ok(())
+        }
+
+        fn iso_out_transfer(
+            &mut self,
+            _ep: &mut dyn Endpoint,
+            _bufs: &[&[u8]],
+            _statuses: &mut [IsoPacketStatus],
+        ) -> Result<(), TransferError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn poll_iso_in_only_issues_the_transfer_at_the_frame_boundary() {
+        let mut ep = MockEndpoint { interval_ms: 10 };
+        let mut host = MockHost::default();
+        let mut schedule = PollSchedule::new(&ep, 0);
+        let mut bufs: [&mut [u8]; 0] = [];
+        let mut statuses: [IsoPacketStatus; 0] = [];
+
+        // Due immediately: the first call should poll.
+        assert!(schedule
+            .poll_iso_in(0, &mut host, &mut ep, &mut bufs, &mut statuses)
+            .unwrap());
+        assert_eq!(host.iso_in_calls, 1);
+
+        // Not yet due: should not poll again.
+        assert!(!schedule
+            .poll_iso_in(5, &mut host, &mut ep, &mut bufs, &mut statuses)
+            .unwrap());
+        assert_eq!(host.iso_in_calls, 1);
+
+        // Frame boundary reached: should poll again.
+        assert!(schedule
+            .poll_iso_in(10, &mut host, &mut ep, &mut bufs, &mut statuses)
+            .unwrap());
+        assert_eq!(host.iso_in_calls, 2);
+    }
+
+    #[test]
+    fn poll_in_only_issues_the_transfer_once_the_interval_elapses() {
+        let mut ep = MockEndpoint { interval_ms: 10 };
+        let mut host = MockHost::default();
+        let mut schedule = PollSchedule::new(&ep, 0);
+        let mut buf = [0u8; 4];
+
+        assert_eq!(
+            schedule.poll_in(0, &mut host, &mut ep, &mut buf).unwrap(),
+            Some(4)
+        );
+        assert_eq!(host.in_calls, 1);
+
+        assert_eq!(schedule.poll_in(5, &mut host, &mut ep, &mut buf).unwrap(), None);
+        assert_eq!(host.in_calls, 1);
+
+        assert_eq!(
+            schedule.poll_in(10, &mut host, &mut ep, &mut buf).unwrap(),
+            Some(4)
+        );
+        assert_eq!(host.in_calls, 2);
+    }
+
+    #[test]
+    fn scheduler_register_and_unregister_reuses_slots() {
+        let ep_a = MockEndpoint { interval_ms: 10 };
+        let ep_b = MockEndpoint { interval_ms: 20 };
+
+        let mut scheduler: PollScheduler<2> = PollScheduler::new();
+        let a = scheduler.register(&ep_a, 0).unwrap();
+        let b = scheduler.register(&ep_b, 0).unwrap();
+        assert!(scheduler.register(&ep_a, 0).is_none(), "no free slots left");
+
+        scheduler.unregister(a);
+        let reused = scheduler
+            .register(&ep_a, 0)
+            .expect("unregistering should free a's slot");
+        assert_eq!(reused, a);
+
+        assert!(scheduler.is_due(b, 0));
+        scheduler.mark_polled(b, 0);
+        assert!(!scheduler.is_due(b, 1));
+    }
+}